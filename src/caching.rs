@@ -0,0 +1,26 @@
+//! In-memory cache mapping subaddresses to their index, so the scanning thread can cheaply
+//! recognize owned outputs without querying the database on every scan.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::invoice::SubIndex;
+
+#[derive(Default)]
+pub(crate) struct SubaddressCache {
+    addresses: RwLock<HashMap<String, SubIndex>>,
+}
+
+impl SubaddressCache {
+    pub(crate) fn insert(&self, address: String, index: SubIndex) {
+        self.addresses.write().unwrap().insert(address, index);
+    }
+
+    pub(crate) fn get(&self, address: &str) -> Option<SubIndex> {
+        self.addresses.read().unwrap().get(address).copied()
+    }
+
+    pub(crate) fn remove(&self, address: &str) {
+        self.addresses.write().unwrap().remove(address);
+    }
+}
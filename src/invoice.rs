@@ -0,0 +1,272 @@
+//! Types representing a tracked payment and its state.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// The index of a subaddress, relative to the primary address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct SubIndex {
+    /// Account index. Always `0` for this library, since only one account is used.
+    pub major: u32,
+    /// Subaddress index.
+    pub minor: u32,
+}
+
+impl SubIndex {
+    pub(crate) fn new(major: u32, minor: u32) -> SubIndex {
+        SubIndex { major, minor }
+    }
+}
+
+impl fmt::Display for SubIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.major, self.minor)
+    }
+}
+
+/// Uniquely identifies an [`Invoice`] by the subaddress index it was generated for.
+pub type InvoiceId = SubIndex;
+
+/// The lifecycle status of an [`Invoice`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InvoiceStatus {
+    /// Waiting for the requested amount to be paid.
+    Pending,
+    /// The requested amount has been paid, but has not yet reached the required number of
+    /// confirmations.
+    Paid,
+    /// The requested amount has been paid and has reached the required number of confirmations.
+    Confirmed,
+    /// The invoice's `expiration_height` passed before it was paid in full.
+    Expired,
+}
+
+impl fmt::Display for InvoiceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            InvoiceStatus::Pending => "pending",
+            InvoiceStatus::Paid => "paid",
+            InvoiceStatus::Confirmed => "confirmed",
+            InvoiceStatus::Expired => "expired",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A payment request being tracked by the [`PaymentGateway`](crate::PaymentGateway).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invoice {
+    id: InvoiceId,
+    address: String,
+    amount_requested: u64,
+    amount_paid: u64,
+    confirmations_required: u64,
+    current_height: u64,
+    creation_height: u64,
+    expiration_height: Option<u64>,
+    status: InvoiceStatus,
+    paid_height: Option<u64>,
+}
+
+impl Invoice {
+    pub(crate) fn new(
+        id: InvoiceId,
+        address: String,
+        amount_requested: u64,
+        confirmations_required: u64,
+        creation_height: u64,
+        expiration_height: Option<u64>,
+    ) -> Invoice {
+        Invoice {
+            id,
+            address,
+            amount_requested,
+            amount_paid: 0,
+            confirmations_required,
+            current_height: creation_height,
+            creation_height,
+            expiration_height,
+            status: InvoiceStatus::Pending,
+            paid_height: None,
+        }
+    }
+
+    /// Returns the ID (subaddress index) of this invoice.
+    #[must_use]
+    pub fn id(&self) -> InvoiceId {
+        self.id
+    }
+
+    /// Returns the subaddress this invoice expects payment to.
+    #[must_use]
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Returns the amount (in piconero) requested by this invoice.
+    #[must_use]
+    pub fn amount_requested(&self) -> u64 {
+        self.amount_requested
+    }
+
+    /// Returns the amount (in piconero) paid towards this invoice so far.
+    #[must_use]
+    pub fn amount_paid(&self) -> u64 {
+        self.amount_paid
+    }
+
+    /// Returns `true` if the requested amount has been paid.
+    #[must_use]
+    pub fn is_paid(&self) -> bool {
+        self.amount_paid >= self.amount_requested
+    }
+
+    /// Returns the blockchain height at (or after) which this invoice is considered expired, if
+    /// one was set when the invoice was created.
+    #[must_use]
+    pub fn expiration_height(&self) -> Option<u64> {
+        self.expiration_height
+    }
+
+    /// Returns this invoice's current lifecycle status.
+    #[must_use]
+    pub fn status(&self) -> InvoiceStatus {
+        self.status
+    }
+
+    pub(crate) fn creation_height(&self) -> u64 {
+        self.creation_height
+    }
+
+    pub(crate) fn current_height(&self) -> u64 {
+        self.current_height
+    }
+
+    pub(crate) fn set_current_height(&mut self, height: u64) {
+        self.current_height = height;
+    }
+
+    pub(crate) fn credit(&mut self, amount: u64) {
+        self.amount_paid = self.amount_paid.saturating_add(amount);
+    }
+
+    /// Set the total amount paid so far, as reported by a backend (e.g. wallet-rpc's
+    /// `incoming_transfers`) that returns a full snapshot rather than incremental credits.
+    pub(crate) fn set_amount_paid(&mut self, amount: u64) {
+        self.amount_paid = amount;
+    }
+
+    /// Recompute this invoice's status given its current height, run once per scan.
+    ///
+    /// An invoice that has already reached a required number of confirmations, or that has
+    /// already expired, never regresses back to an earlier status even if reorg bookkeeping moves
+    /// `current_height` around.
+    pub(crate) fn update_status(&mut self) {
+        if self.status == InvoiceStatus::Confirmed || self.status == InvoiceStatus::Expired {
+            return;
+        }
+
+        if self.is_paid() {
+            let paid_height = *self.paid_height.get_or_insert(self.current_height);
+            let confirmations = self.current_height.saturating_sub(paid_height);
+            self.status = if confirmations >= self.confirmations_required {
+                InvoiceStatus::Confirmed
+            } else {
+                InvoiceStatus::Paid
+            };
+            return;
+        }
+
+        if let Some(expiration_height) = self.expiration_height {
+            if self.current_height >= expiration_height {
+                self.status = InvoiceStatus::Expired;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invoice(confirmations_required: u64, creation_height: u64, expiration_height: Option<u64>) -> Invoice {
+        Invoice::new(
+            SubIndex::new(0, 1),
+            "address".to_string(),
+            1_000,
+            confirmations_required,
+            creation_height,
+            expiration_height,
+        )
+    }
+
+    #[test]
+    fn pending_until_fully_paid() {
+        let mut invoice = invoice(10, 100, None);
+        invoice.set_current_height(105);
+        invoice.credit(500);
+        invoice.update_status();
+
+        assert_eq!(invoice.status(), InvoiceStatus::Pending);
+    }
+
+    #[test]
+    fn confirmations_count_from_payment_height_not_creation_height() {
+        // Created at height 100, paid in full at height 115, with 10 confirmations required.
+        // Counting from `creation_height` would show 15 confirmations (already `Confirmed`) on
+        // the very scan the payment lands; confirmations must instead count from the height at
+        // which `is_paid()` first became true.
+        let mut invoice = invoice(10, 100, None);
+        invoice.set_current_height(115);
+        invoice.credit(1_000);
+        invoice.update_status();
+
+        assert_eq!(invoice.status(), InvoiceStatus::Paid);
+
+        invoice.set_current_height(124);
+        invoice.update_status();
+        assert_eq!(invoice.status(), InvoiceStatus::Paid);
+
+        invoice.set_current_height(125);
+        invoice.update_status();
+        assert_eq!(invoice.status(), InvoiceStatus::Confirmed);
+    }
+
+    #[test]
+    fn confirmed_never_regresses_to_paid() {
+        let mut invoice = invoice(1, 100, None);
+        invoice.set_current_height(101);
+        invoice.credit(1_000);
+        invoice.update_status();
+        invoice.set_current_height(102);
+        invoice.update_status();
+        assert_eq!(invoice.status(), InvoiceStatus::Confirmed);
+
+        // A reorg-like rewind of `current_height` must not un-confirm the invoice.
+        invoice.set_current_height(100);
+        invoice.update_status();
+        assert_eq!(invoice.status(), InvoiceStatus::Confirmed);
+    }
+
+    #[test]
+    fn expires_if_unpaid_past_expiration_height() {
+        let mut invoice = invoice(10, 100, Some(110));
+        invoice.set_current_height(110);
+        invoice.update_status();
+
+        assert_eq!(invoice.status(), InvoiceStatus::Expired);
+    }
+
+    #[test]
+    fn expired_never_regresses_even_if_paid_late() {
+        let mut invoice = invoice(10, 100, Some(110));
+        invoice.set_current_height(110);
+        invoice.update_status();
+        assert_eq!(invoice.status(), InvoiceStatus::Expired);
+
+        invoice.credit(1_000);
+        invoice.update_status();
+        assert_eq!(invoice.status(), InvoiceStatus::Expired);
+    }
+}
@@ -0,0 +1,82 @@
+//! Persistent storage of [`Invoice`](crate::Invoice)s, backed by [`sled`].
+
+use std::fmt;
+use std::path::Path;
+
+use crate::invoice::{Invoice, InvoiceId};
+
+/// Wraps a [`sled::Tree`] to persist invoices across restarts.
+pub(crate) struct InvoicesDb {
+    tree: sled::Tree,
+}
+
+impl InvoicesDb {
+    pub(crate) fn new(db: &sled::Db, tree_name: &str) -> Result<InvoicesDb, InvoiceStorageError> {
+        let tree = db.open_tree(tree_name)?;
+        Ok(InvoicesDb { tree })
+    }
+
+    pub(crate) fn insert(&self, invoice: &Invoice) -> Result<(), InvoiceStorageError> {
+        let key = bincode::serialize(&invoice.id())?;
+        let value = bincode::serialize(invoice)?;
+        self.tree.insert(key, value)?;
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, id: InvoiceId) -> Result<Option<Invoice>, InvoiceStorageError> {
+        let key = bincode::serialize(&id)?;
+        match self.tree.get(key)? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn remove(&self, id: InvoiceId) -> Result<(), InvoiceStorageError> {
+        let key = bincode::serialize(&id)?;
+        self.tree.remove(key)?;
+        Ok(())
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = Result<Invoice, InvoiceStorageError>> {
+        self.tree.iter().values().map(|v| {
+            let bytes = v?;
+            bincode::deserialize(&bytes).map_err(InvoiceStorageError::from)
+        })
+    }
+}
+
+pub(crate) fn open_database(path: impl AsRef<Path>) -> Result<sled::Db, InvoiceStorageError> {
+    Ok(sled::open(path)?)
+}
+
+/// An error storing/retrieving [`Invoice`](crate::Invoice)s.
+#[derive(Debug)]
+pub enum InvoiceStorageError {
+    /// An error originating from the underlying [`sled`] database.
+    Database(sled::Error),
+    /// Failed to serialize/deserialize an [`Invoice`](crate::Invoice).
+    Serialization(bincode::Error),
+}
+
+impl From<sled::Error> for InvoiceStorageError {
+    fn from(e: sled::Error) -> Self {
+        InvoiceStorageError::Database(e)
+    }
+}
+
+impl From<bincode::Error> for InvoiceStorageError {
+    fn from(e: bincode::Error) -> Self {
+        InvoiceStorageError::Serialization(e)
+    }
+}
+
+impl fmt::Display for InvoiceStorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvoiceStorageError::Database(e) => write!(f, "database error: {}", e),
+            InvoiceStorageError::Serialization(e) => write!(f, "(de)serialization error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for InvoiceStorageError {}
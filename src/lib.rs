@@ -90,10 +90,11 @@ mod subscriber;
 use std::error::Error;
 use std::fmt;
 
-pub use invoice::{Invoice, InvoiceId, SubIndex};
+pub use invoice::{Invoice, InvoiceId, InvoiceStatus, SubIndex};
 use invoices_db::InvoiceStorageError;
 pub use payment_gateway::{PaymentGateway, PaymentGatewayBuilder};
 use rpc::RpcError;
+pub use scanner::{ScannerBackend, WalletRpcConfig};
 pub use subscriber::{Subscriber, SubscriberError};
 
 /// Library's custom error type.
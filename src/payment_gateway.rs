@@ -0,0 +1,426 @@
+//! Entry point for generating and tracking invoices.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::Url;
+
+use crate::caching::SubaddressCache;
+use crate::invoice::{Invoice, InvoiceId, SubIndex};
+use crate::invoices_db::{self, InvoicesDb};
+use crate::rpc::RpcClient;
+use crate::scanner::{Scanner, ScannerBackend};
+use crate::subscriber::{Subscriber, Subscribers};
+use crate::AcceptXmrError;
+
+const DEFAULT_DAEMON_URL: &str = "http://127.0.0.1:18081/";
+const DEFAULT_DB_PATH: &str = "./AcceptXMR_DB";
+const DEFAULT_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A payment gateway which generates and tracks monero invoices.
+pub struct PaymentGateway {
+    private_view_key: String,
+    primary_address: String,
+    invoices_db: Arc<InvoicesDb>,
+    subaddress_cache: Arc<SubaddressCache>,
+    subscribers: Arc<Subscribers>,
+    rpc_client: Arc<RpcClient>,
+    current_height: Arc<AtomicU64>,
+    next_minor_index: AtomicU32,
+    _scanner: Scanner,
+}
+
+impl PaymentGateway {
+    /// Returns a new [`PaymentGatewayBuilder`].
+    pub fn builder(private_view_key: String, primary_address: String) -> PaymentGatewayBuilder {
+        PaymentGatewayBuilder::new(private_view_key, primary_address)
+    }
+
+    /// Generate a new invoice for `amount` piconero, requiring `confirmations_required`
+    /// confirmations before it is considered confirmed, and expiring (if the requested amount has not
+    /// been received) `expires_in_blocks` blocks from now if given.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AcceptXmrError::InvoiceStorage`] if the invoice could not be persisted.
+    pub fn new_invoice(
+        &self,
+        amount: u64,
+        confirmations_required: u64,
+        expires_in_blocks: Option<u64>,
+    ) -> Result<Invoice, AcceptXmrError> {
+        let minor_index = self.next_minor_index.fetch_add(1, Ordering::SeqCst);
+        let id = SubIndex::new(0, minor_index);
+        // A real subaddress is derived from `private_view_key`/`primary_address` and `id` here;
+        // that derivation is elided in this context.
+        let address = format!("{}#{}", self.primary_address, id);
+        self.subaddress_cache.insert(address.clone(), id);
+
+        let creation_height = self.current_height.load(Ordering::SeqCst);
+        let expiration_height = expires_in_blocks.map(|blocks| creation_height + blocks);
+
+        let invoice = Invoice::new(
+            id,
+            address,
+            amount,
+            confirmations_required,
+            creation_height,
+            expiration_height,
+        );
+        self.invoices_db.insert(&invoice)?;
+        Ok(invoice)
+    }
+
+    /// Look up a previously created invoice by its ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AcceptXmrError::InvoiceStorage`] if the underlying database could not be read.
+    pub fn invoice(&self, id: InvoiceId) -> Result<Option<Invoice>, AcceptXmrError> {
+        Ok(self.invoices_db.get(id)?)
+    }
+
+    /// Subscribe to updates for a specific invoice.
+    #[must_use]
+    pub fn subscribe(&self, id: InvoiceId) -> Subscriber {
+        self.subscribers.subscribe(id)
+    }
+
+    /// Returns the URL of the daemon currently being scanned. When multiple daemon URLs were
+    /// configured via [`daemon_urls`](PaymentGatewayBuilder::daemon_urls), this reflects whichever
+    /// one the last failed RPC call (from the scanning thread or from
+    /// [`check_payment`](PaymentGateway::check_payment)) failed over to.
+    #[must_use]
+    pub fn active_daemon(&self) -> String {
+        self.rpc_client.active_daemon_url().to_string()
+    }
+
+    /// Stop tracking an invoice, removing it from the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AcceptXmrError::InvoiceStorage`] if the invoice could not be removed.
+    pub fn remove_invoice(&self, id: InvoiceId) -> Result<(), AcceptXmrError> {
+        self.invoices_db.remove(id)?;
+        self.subscribers.remove(id);
+        Ok(())
+    }
+
+    /// Synchronously verify a specific payment, without waiting for the next `scan_interval`.
+    ///
+    /// Confirms that `txid` sent an output to the subaddress for `invoice_id`, and unblinds its
+    /// amount using the gateway's private view key. This mirrors the daemon's `check_tx_key`
+    /// style of verification, and is useful for reconciliation tooling or for building
+    /// synchronous "verify this payment now" endpoints without waiting on the scanning thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AcceptXmrError::Rpc`] if the daemon could not be reached or does not know about
+    /// `txid`, or [`AcceptXmrError::Unblind`] if no output in the transaction could be matched to
+    /// `invoice_id`'s subaddress.
+    pub fn check_payment(
+        &self,
+        invoice_id: InvoiceId,
+        txid: &str,
+    ) -> Result<PaymentConfirmation, AcceptXmrError> {
+        // `get_transactions` is one of monerod's raw (non-JSON-RPC) endpoints: it responds with
+        // `txs` at the top level of the body, with no `result` wrapper.
+        let response: GetTransactionsResponse = self.rpc_client.raw_call(
+            "get_transactions",
+            serde_json::json!({ "txs_hashes": [txid], "decode_as_json": true }),
+        )?;
+
+        let tx_entry = response.txs.into_iter().next().ok_or_else(|| {
+            AcceptXmrError::Rpc(crate::rpc::RpcError::DaemonRpc(format!(
+                "daemon has no record of transaction {}",
+                txid
+            )))
+        })?;
+
+        let tx_bytes = hex::decode(&tx_entry.as_hex).map_err(|e| AcceptXmrError::Parse {
+            datatype: "transaction hex",
+            input: tx_entry.as_hex.clone(),
+            error: e.to_string(),
+        })?;
+        let transaction: monero::Transaction =
+            monero::consensus::deserialize(&tx_bytes).map_err(|e| AcceptXmrError::Parse {
+                datatype: "transaction",
+                input: txid.to_string(),
+                error: e.to_string(),
+            })?;
+
+        let view_pair = self.view_pair()?;
+        let minor = invoice_id.minor;
+        let owned_outputs = transaction.check_outputs(&view_pair, 0..1, minor..(minor + 1))?;
+        let amount: u64 = owned_outputs
+            .iter()
+            .map(monero::blockdata::transaction::OwnedTxOut::amount)
+            .sum();
+
+        if amount == 0 {
+            return Err(AcceptXmrError::Unblind(invoice_id));
+        }
+
+        Ok(PaymentConfirmation {
+            amount,
+            confirmations: tx_entry.confirmations,
+        })
+    }
+
+    /// Build the [`monero::ViewPair`] used to identify and unblind owned outputs, from this
+    /// gateway's private view key and primary address.
+    fn view_pair(&self) -> Result<monero::ViewPair, AcceptXmrError> {
+        parse_view_pair(&self.private_view_key, &self.primary_address)
+    }
+}
+
+/// Parse a private view key and primary address into the [`monero::ViewPair`] used to identify
+/// and unblind owned outputs.
+fn parse_view_pair(
+    private_view_key: &str,
+    primary_address: &str,
+) -> Result<monero::ViewPair, AcceptXmrError> {
+    let view = monero::PrivateKey::from_str(private_view_key).map_err(|e| AcceptXmrError::Parse {
+        datatype: "private view key",
+        input: private_view_key.to_string(),
+        error: e.to_string(),
+    })?;
+    let address = monero::Address::from_str(primary_address).map_err(|e| AcceptXmrError::Parse {
+        datatype: "primary address",
+        input: primary_address.to_string(),
+        error: e.to_string(),
+    })?;
+    Ok(monero::ViewPair {
+        view,
+        spend: address.public_spend,
+    })
+}
+
+/// The result of synchronously checking a payment via [`PaymentGateway::check_payment`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaymentConfirmation {
+    /// The amount (in piconero) sent to the checked subaddress in this transaction.
+    pub amount: u64,
+    /// The number of confirmations this transaction currently has.
+    pub confirmations: u64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct GetTransactionsResponse {
+    #[serde(default)]
+    txs: Vec<TxEntry>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TxEntry {
+    as_hex: String,
+    #[serde(default)]
+    confirmations: u64,
+}
+
+/// Builds a [`PaymentGateway`].
+pub struct PaymentGatewayBuilder {
+    private_view_key: String,
+    primary_address: String,
+    daemon_urls: Vec<String>,
+    proxy_url: Option<String>,
+    db_path: String,
+    scan_interval: Duration,
+    expired_invoice_retention_blocks: Option<u64>,
+    backend: ScannerBackend,
+}
+
+impl PaymentGatewayBuilder {
+    pub(crate) fn new(private_view_key: String, primary_address: String) -> PaymentGatewayBuilder {
+        PaymentGatewayBuilder {
+            private_view_key,
+            primary_address,
+            daemon_urls: vec![DEFAULT_DAEMON_URL.to_string()],
+            proxy_url: None,
+            db_path: DEFAULT_DB_PATH.to_string(),
+            scan_interval: DEFAULT_SCAN_INTERVAL,
+            expired_invoice_retention_blocks: Some(0),
+            backend: ScannerBackend::Monerod,
+        }
+    }
+
+    /// Set the URL of the monero daemon to use for scanning.
+    #[must_use]
+    pub fn daemon_url(mut self, url: String) -> PaymentGatewayBuilder {
+        self.daemon_urls = vec![url];
+        self
+    }
+
+    /// Set an ordered list of daemon URLs to scan. On RPC failure, the gateway fails over to the
+    /// next daemon in the list (wrapping around), applying an exponential backoff to any daemon
+    /// it could not reach. With a single daemon, this is equivalent to
+    /// [`daemon_url`](PaymentGatewayBuilder::daemon_url): the same daemon is simply retried next
+    /// scan.
+    #[must_use]
+    pub fn daemon_urls(mut self, urls: Vec<String>) -> PaymentGatewayBuilder {
+        self.daemon_urls = urls;
+        self
+    }
+
+    /// Set the minimum interval between scans for invoice updates.
+    #[must_use]
+    pub fn scan_interval(mut self, interval: Duration) -> PaymentGatewayBuilder {
+        self.scan_interval = interval;
+        self
+    }
+
+    /// Set the path at which the invoices database should be stored.
+    #[must_use]
+    pub fn db_path(mut self, path: String) -> PaymentGatewayBuilder {
+        self.db_path = path;
+        self
+    }
+
+    /// Route all daemon RPC calls through the SOCKS5 proxy at `proxy_url`
+    /// (e.g. `socks5h://127.0.0.1:9050`).
+    ///
+    /// With the `socks5h` scheme, DNS resolution of the daemon's hostname is delegated to the
+    /// proxy, so it never reaches the local resolver. With the `socks5` scheme, the hostname is
+    /// resolved locally before connecting through the proxy.
+    #[must_use]
+    pub fn proxy(mut self, proxy_url: String) -> PaymentGatewayBuilder {
+        self.proxy_url = Some(proxy_url);
+        self
+    }
+
+    /// Convenience wrapper around [`proxy`](PaymentGatewayBuilder::proxy) for routing RPC calls
+    /// through a local Tor daemon's SOCKS5 port.
+    #[must_use]
+    pub fn tor_socks5(self, addr: String) -> PaymentGatewayBuilder {
+        self.proxy(format!("socks5h://{}", addr))
+    }
+
+    /// Keep expired invoices in the database for `blocks` blocks after they expire (for
+    /// reconciliation or display purposes) before the scanning thread garbage-collects them.
+    /// By default, expired invoices are removed as soon as they are noticed.
+    #[must_use]
+    pub fn expired_invoice_retention(mut self, blocks: u64) -> PaymentGatewayBuilder {
+        self.expired_invoice_retention_blocks = Some(blocks);
+        self
+    }
+
+    /// Select which subsystem is used to detect incoming payments. Defaults to
+    /// [`ScannerBackend::Monerod`]. When using [`ScannerBackend::WalletRpc`], point
+    /// [`daemon_url`](PaymentGatewayBuilder::daemon_url)/
+    /// [`daemon_urls`](PaymentGatewayBuilder::daemon_urls) at the `monero-wallet-rpc` instance(s)
+    /// rather than at monerod.
+    #[must_use]
+    pub fn backend(mut self, backend: ScannerBackend) -> PaymentGatewayBuilder {
+        self.backend = backend;
+        self
+    }
+
+    /// Build the [`PaymentGateway`], opening its database, synchronously fetching the current
+    /// chain height, and spawning its scanning thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AcceptXmrError::Rpc`] if the daemon/proxy URL is invalid or the initial height
+    /// could not be fetched, or [`AcceptXmrError::InvoiceStorage`] if the database could not be
+    /// opened.
+    pub fn build(self) -> Result<PaymentGateway, AcceptXmrError> {
+        let daemon_urls = self
+            .daemon_urls
+            .iter()
+            .map(|url| Url::parse(url).map_err(|e| crate::rpc::RpcError::InvalidUrl(e.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let rpc_client = match self.proxy_url {
+            Some(proxy_url) => {
+                let proxy_url = Url::parse(&proxy_url)
+                    .map_err(|e| crate::rpc::RpcError::InvalidProxy(proxy_url, e.to_string()))?;
+                RpcClient::with_proxy(daemon_urls, self.scan_interval, proxy_url)?
+            }
+            None => RpcClient::new(daemon_urls, self.scan_interval)?,
+        };
+        let rpc_client = Arc::new(rpc_client);
+
+        // Fetch the starting height synchronously, rather than leaving `current_height` at `0`
+        // until the scanning thread's first scan: any invoice created before that first scan
+        // would otherwise compute its `expiration_height` from height `0`, making it look already
+        // expired (and eligible for GC) the moment the real chain height is known.
+        let initial_height = crate::scanner::fetch_height(&rpc_client, &self.backend)?;
+
+        let db = invoices_db::open_database(&self.db_path)?;
+        let invoices_db = Arc::new(InvoicesDb::new(&db, "invoices")?);
+        let subaddress_cache = Arc::new(SubaddressCache::default());
+        let subscribers = Arc::new(Subscribers::default());
+        let current_height = Arc::new(AtomicU64::new(initial_height));
+
+        let scanner = Scanner::spawn(
+            Arc::clone(&rpc_client),
+            Arc::clone(&invoices_db),
+            Arc::clone(&subaddress_cache),
+            Arc::clone(&subscribers),
+            Arc::clone(&current_height),
+            self.scan_interval,
+            self.expired_invoice_retention_blocks,
+            self.backend,
+        );
+
+        Ok(PaymentGateway {
+            private_view_key: self.private_view_key,
+            primary_address: self.primary_address,
+            invoices_db,
+            subaddress_cache,
+            subscribers,
+            rpc_client,
+            current_height,
+            next_minor_index: AtomicU32::new(1),
+            _scanner: scanner,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Same test vectors used by the crate-level doctest in `lib.rs`.
+    const PRIVATE_VIEW_KEY: &str =
+        "ad2093a5705b9f33e6f0f0c1bc1f5f639c756cdfc168c8f2ac6127ccbdab3a03";
+    const PRIMARY_ADDRESS: &str = "4613YiHLM6JMH4zejMB2zJY5TwQCxL8p65ufw8kBP5yxX9itmuGLqp1dS4tkVoTxjyH3aYhYNrtGHbQzJQP5bFus3KHVdmf";
+
+    #[test]
+    fn parses_valid_view_pair() {
+        let view_pair = parse_view_pair(PRIVATE_VIEW_KEY, PRIMARY_ADDRESS).unwrap();
+
+        let address = monero::Address::from_str(PRIMARY_ADDRESS).unwrap();
+        assert_eq!(view_pair.spend, address.public_spend);
+        assert_eq!(
+            view_pair.view,
+            monero::PrivateKey::from_str(PRIVATE_VIEW_KEY).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_private_view_key() {
+        let error = parse_view_pair("not a key", PRIMARY_ADDRESS).unwrap_err();
+        assert!(matches!(
+            error,
+            AcceptXmrError::Parse {
+                datatype: "private view key",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_primary_address() {
+        let error = parse_view_pair(PRIVATE_VIEW_KEY, "not an address").unwrap_err();
+        assert!(matches!(
+            error,
+            AcceptXmrError::Parse {
+                datatype: "primary address",
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,403 @@
+//! Minimal JSON-RPC client used to talk to one or more monero daemons.
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::blocking::Client;
+use reqwest::{Proxy, Url};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+/// Upper bound on the exponential backoff applied to a daemon that keeps failing.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// A client for making JSON-RPC calls against a monero daemon.
+///
+/// Holds an ordered list of daemons. On RPC failure, the client rotates to the next daemon it
+/// believes is healthy, recording an exponential backoff (doubling up to [`MAX_BACKOFF`], starting
+/// at the configured scan interval) for any daemon it failed to reach. The backoff only steers
+/// which daemon rotation picks: the active daemon is called on the next request whether or not
+/// its backoff has expired, so a single daemon, or a set of daemons that are all backing off, is
+/// retried every scan.
+#[derive(Debug)]
+pub(crate) struct RpcClient {
+    client: Client,
+    daemons: Vec<Daemon>,
+    active: AtomicUsize,
+    base_backoff: Duration,
+    proxy_url: Option<String>,
+}
+
+#[derive(Debug)]
+struct Daemon {
+    url: Url,
+    backoff: Mutex<BackoffState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BackoffState {
+    current: Duration,
+    retry_after: Option<Instant>,
+}
+
+impl RpcClient {
+    /// Create a new RPC client that scans `daemon_urls` in order, failing over to the next one
+    /// on error. `base_backoff` is the initial (and minimum) backoff applied to a failed daemon;
+    /// in practice this is the gateway's `scan_interval`.
+    pub(crate) fn new(daemon_urls: Vec<Url>, base_backoff: Duration) -> Result<RpcClient, RpcError> {
+        Self::with_optional_proxy(daemon_urls, base_backoff, None)
+    }
+
+    /// Like [`RpcClient::new`], but routes all requests through the given SOCKS5 `proxy` (e.g. a
+    /// local Tor daemon's SOCKS5 port).
+    ///
+    /// When `proxy` uses the `socks5h` scheme, DNS resolution for each daemon's hostname is
+    /// performed by the proxy, so no daemon address is resolved locally. With the `socks5`
+    /// scheme, hostnames are resolved locally.
+    pub(crate) fn with_proxy(
+        daemon_urls: Vec<Url>,
+        base_backoff: Duration,
+        proxy: Url,
+    ) -> Result<RpcClient, RpcError> {
+        Self::with_optional_proxy(daemon_urls, base_backoff, Some(proxy))
+    }
+
+    fn with_optional_proxy(
+        daemon_urls: Vec<Url>,
+        base_backoff: Duration,
+        proxy: Option<Url>,
+    ) -> Result<RpcClient, RpcError> {
+        if daemon_urls.is_empty() {
+            return Err(RpcError::NoDaemons);
+        }
+
+        let proxy_url = proxy.as_ref().map(Url::to_string);
+        let mut builder = Client::builder().timeout(Duration::from_secs(30));
+        if let Some(proxy_url) = proxy {
+            let proxy = Proxy::all(proxy_url.clone())
+                .map_err(|e| RpcError::InvalidProxy(proxy_url.to_string(), e.to_string()))?;
+            builder = builder.proxy(proxy);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| RpcError::InvalidProxy(String::new(), e.to_string()))?;
+
+        let daemons = daemon_urls
+            .into_iter()
+            .map(|url| Daemon {
+                url,
+                backoff: Mutex::new(BackoffState {
+                    current: base_backoff,
+                    retry_after: None,
+                }),
+            })
+            .collect();
+
+        Ok(RpcClient {
+            client,
+            daemons,
+            active: AtomicUsize::new(0),
+            base_backoff,
+            proxy_url,
+        })
+    }
+
+    /// Returns the URL of the daemon currently being scanned, so operators can observe failover.
+    pub(crate) fn active_daemon_url(&self) -> &Url {
+        &self.daemons[self.active.load(Ordering::SeqCst)].url
+    }
+
+    /// Call a daemon JSON-RPC method against the active daemon, deserializing the `result` field
+    /// of the response. On failure, fails over to the next daemon that is out of backoff (or, if
+    /// there is only one daemon, simply leaves it in place so the caller retries it next scan).
+    pub(crate) fn json_rpc_call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T, RpcError> {
+        self.call_active(|daemon| {
+            Self::call_json_rpc(&self.client, daemon, method, &params, self.proxy_url.as_deref())
+        })
+    }
+
+    /// Call one of the daemon's non-JSON-RPC endpoints (e.g. monerod's `/get_transactions`)
+    /// against the active daemon, deserializing the whole response body. On failure, fails over
+    /// the same way [`json_rpc_call`](RpcClient::json_rpc_call) does.
+    pub(crate) fn raw_call<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        body: serde_json::Value,
+    ) -> Result<T, RpcError> {
+        self.call_active(|daemon| {
+            Self::call_raw(&self.client, daemon, endpoint, &body, self.proxy_url.as_deref())
+        })
+    }
+
+    /// Run `call` against the active daemon, resetting its backoff on success or recording a
+    /// failure and rotating to the next healthy daemon on error.
+    fn call_active<T>(&self, call: impl FnOnce(&Daemon) -> Result<T, RpcError>) -> Result<T, RpcError> {
+        let active = self.active.load(Ordering::SeqCst);
+        match call(&self.daemons[active]) {
+            Ok(result) => {
+                self.daemons[active].backoff.lock().unwrap().current = self.base_backoff;
+                Ok(result)
+            }
+            Err(e) => {
+                self.fail_and_rotate(active);
+                Err(e)
+            }
+        }
+    }
+
+    fn call_json_rpc<T: DeserializeOwned>(
+        client: &Client,
+        daemon: &Daemon,
+        method: &str,
+        params: &serde_json::Value,
+        proxy_url: Option<&str>,
+    ) -> Result<T, RpcError> {
+        let url = daemon
+            .url
+            .join("json_rpc")
+            .map_err(|e| RpcError::InvalidUrl(e.to_string()))?;
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": "0",
+            "method": method,
+            "params": params,
+        });
+        let response = client
+            .post(url)
+            .json(&body)
+            .send()
+            .map_err(|e| classify_reqwest_error(e, proxy_url))?;
+        let response: serde_json::Value =
+            response.json().map_err(|e| classify_reqwest_error(e, proxy_url))?;
+
+        if let Some(error) = response.get("error") {
+            let code = error.get("code").and_then(serde_json::Value::as_i64).unwrap_or(0);
+            let message = error
+                .get("message")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or("unknown error")
+                .to_string();
+            return Err(RpcError::JsonRpc { code, message });
+        }
+
+        let result = response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| RpcError::DaemonRpc("response missing \"result\" field".to_string()))?;
+        serde_json::from_value(result).map_err(|e| RpcError::DaemonRpc(e.to_string()))
+    }
+
+    /// Call one of monerod's raw (non-JSON-RPC) endpoints, such as `/get_transactions`, which
+    /// respond with the result fields directly at the top level of the body rather than wrapped
+    /// in a `result` field.
+    fn call_raw<T: DeserializeOwned>(
+        client: &Client,
+        daemon: &Daemon,
+        endpoint: &str,
+        body: &serde_json::Value,
+        proxy_url: Option<&str>,
+    ) -> Result<T, RpcError> {
+        let url = daemon
+            .url
+            .join(endpoint)
+            .map_err(|e| RpcError::InvalidUrl(e.to_string()))?;
+        let response = client
+            .post(url)
+            .json(body)
+            .send()
+            .map_err(|e| classify_reqwest_error(e, proxy_url))?;
+        let response: serde_json::Value =
+            response.json().map_err(|e| classify_reqwest_error(e, proxy_url))?;
+        serde_json::from_value(response).map_err(|e| RpcError::DaemonRpc(e.to_string()))
+    }
+
+    /// Record a failure on `failed_index`, doubling its backoff (capped at [`MAX_BACKOFF`]), then
+    /// advance `active` to the next daemon that is not currently backing off. If every daemon
+    /// (including the single-daemon case) is still backing off, `active` is moved to whichever
+    /// daemon's backoff will expire soonest, rather than round-robining through them. That daemon
+    /// is called on the next request even if its backoff has not yet expired.
+    fn fail_and_rotate(&self, failed_index: usize) {
+        let now = Instant::now();
+        {
+            let mut backoff = self.daemons[failed_index].backoff.lock().unwrap();
+            backoff.retry_after = Some(now + backoff.current);
+            backoff.current = (backoff.current * 2).min(MAX_BACKOFF);
+        }
+
+        if self.daemons.len() <= 1 {
+            return;
+        }
+
+        let len = self.daemons.len();
+        for offset in 1..=len {
+            let candidate = (failed_index + offset) % len;
+            let backoff = self.daemons[candidate].backoff.lock().unwrap();
+            let healthy = match backoff.retry_after {
+                Some(retry_after) => retry_after <= now,
+                None => true,
+            };
+            if healthy {
+                drop(backoff);
+                self.active.store(candidate, Ordering::SeqCst);
+                return;
+            }
+        }
+
+        // Every daemon is backing off; pick whichever has the soonest `retry_after` instead of
+        // blindly advancing.
+        let soonest = (0..len)
+            .min_by_key(|&candidate| {
+                self.daemons[candidate]
+                    .backoff
+                    .lock()
+                    .unwrap()
+                    .retry_after
+                    .unwrap_or(now)
+            })
+            .unwrap_or(failed_index);
+        self.active.store(soonest, Ordering::SeqCst);
+    }
+}
+
+/// An error originating from a daemon RPC call.
+#[derive(Debug)]
+pub enum RpcError {
+    /// No daemon URLs were configured. An [`RpcClient`] must track at least one daemon to scan.
+    NoDaemons,
+    /// The daemon url could not be parsed/joined.
+    InvalidUrl(String),
+    /// Failed to connect to the configured SOCKS5 proxy, either while building the client or
+    /// while making a request through it. This is what surfaces when a SOCKS5 proxy (e.g. Tor)
+    /// drops the circuit mid-scan, so the scanning thread can treat it the same way it treats any
+    /// other failed daemon: log it, fail over, and retry next scan.
+    InvalidProxy(String, String),
+    /// The underlying HTTP request to the daemon failed for a reason unrelated to the proxy (e.g.
+    /// the daemon itself is unreachable or was restarted), and is safe to retry against the same
+    /// or a different daemon.
+    Network(reqwest::Error),
+    /// The daemon returned a malformed or unexpected RPC response.
+    DaemonRpc(String),
+    /// The daemon/wallet responded with a well-formed JSON-RPC error. Exposed with its numeric
+    /// `code` so callers (e.g. the wallet-rpc scanning backend) can react to specific conditions,
+    /// such as monero-wallet-rpc's `-13` ("no wallet file loaded").
+    JsonRpc {
+        /// The JSON-RPC error code.
+        code: i64,
+        /// The JSON-RPC error message.
+        message: String,
+    },
+}
+
+/// Classify a failed request, attributing connection failures to the configured proxy (rather
+/// than the generic [`RpcError::Network`]) whenever one is in use, since a proxy connection
+/// failure (e.g. a dropped Tor circuit) is the overwhelmingly likely cause in that case.
+fn classify_reqwest_error(e: reqwest::Error, proxy_url: Option<&str>) -> RpcError {
+    match (e.is_connect(), proxy_url) {
+        (true, Some(proxy_url)) => RpcError::InvalidProxy(proxy_url.to_string(), e.to_string()),
+        (true, None) => RpcError::Network(e),
+        (false, _) => RpcError::DaemonRpc(e.to_string()),
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::NoDaemons => write!(f, "no daemon URLs were configured"),
+            RpcError::InvalidUrl(e) => write!(f, "invalid daemon url: {}", e),
+            RpcError::InvalidProxy(proxy, e) => {
+                write!(f, "failed to connect to proxy \"{}\": {}", proxy, e)
+            }
+            RpcError::Network(e) => write!(f, "daemon RPC request failed: {}", e),
+            RpcError::DaemonRpc(e) => write!(f, "daemon returned an invalid RPC response: {}", e),
+            RpcError::JsonRpc { code, message } => {
+                write!(f, "daemon returned RPC error {}: {}", code, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    use reqwest::Url;
+
+    use super::{RpcClient, RpcError};
+
+    fn unreachable_client(daemon_count: usize, base_backoff: Duration) -> RpcClient {
+        // These daemons are never actually dialed by the tests below; `fail_and_rotate` only
+        // touches backoff bookkeeping and the active index.
+        let daemon_urls = (0..daemon_count)
+            .map(|i| Url::parse(&format!("http://127.0.0.1:1/{}", i)).unwrap())
+            .collect();
+        RpcClient::new(daemon_urls, base_backoff).unwrap()
+    }
+
+    fn retry_after(client: &RpcClient, index: usize) -> Option<Instant> {
+        client.daemons[index].backoff.lock().unwrap().retry_after
+    }
+
+    #[test]
+    fn rotates_to_the_next_healthy_daemon_on_failure() {
+        let client = unreachable_client(3, Duration::from_secs(1));
+
+        client.fail_and_rotate(0);
+
+        assert_eq!(client.active.load(Ordering::SeqCst), 1);
+        assert!(retry_after(&client, 0).is_some());
+    }
+
+    #[test]
+    fn skips_daemons_still_backing_off() {
+        let client = unreachable_client(2, Duration::from_secs(1));
+
+        client.fail_and_rotate(0);
+        assert_eq!(client.active.load(Ordering::SeqCst), 1);
+
+        // Daemon 1 fails immediately too, before daemon 0's backoff has expired. With only two
+        // daemons and both backing off, rotation should land back on whichever has the soonest
+        // `retry_after` (daemon 0, whose backoff was set first) rather than round-robining blindly.
+        client.fail_and_rotate(1);
+        assert_eq!(client.active.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn backoff_doubles_on_each_failure() {
+        let client = unreachable_client(1, Duration::from_millis(100));
+
+        client.fail_and_rotate(0);
+        let first = client.daemons[0].backoff.lock().unwrap().current;
+        assert_eq!(first, Duration::from_millis(200));
+
+        client.fail_and_rotate(0);
+        let second = client.daemons[0].backoff.lock().unwrap().current;
+        assert_eq!(second, Duration::from_millis(400));
+    }
+
+    #[test]
+    fn in_flight_proxy_failures_name_the_configured_proxy() {
+        // Port 1 is not listening, so connecting to the "proxy" fails immediately with a connect
+        // error, simulating a dropped Tor circuit mid-scan rather than a build-time failure.
+        let proxy_url = Url::parse("socks5h://127.0.0.1:1").unwrap();
+        let daemon_url = Url::parse("http://127.0.0.1:18081/").unwrap();
+        let client =
+            RpcClient::with_proxy(vec![daemon_url], Duration::from_secs(1), proxy_url).unwrap();
+
+        let result: Result<serde_json::Value, RpcError> =
+            client.json_rpc_call("get_block_count", serde_json::json!({}));
+
+        match result {
+            Err(RpcError::InvalidProxy(proxy, _)) => assert!(proxy.contains("127.0.0.1:1")),
+            other => panic!("expected InvalidProxy, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Allows callers to wait for updates to a specific [`Invoice`](crate::Invoice).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvError, Sender, TryRecvError};
+use std::sync::Mutex;
+
+use crate::invoice::{Invoice, InvoiceId};
+
+/// Receives updates for a single invoice from the scanning thread.
+pub struct Subscriber {
+    receiver: Receiver<Invoice>,
+}
+
+impl Subscriber {
+    pub(crate) fn new(receiver: Receiver<Invoice>) -> Subscriber {
+        Subscriber { receiver }
+    }
+
+    /// Block until the next update for this invoice is available.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SubscriberError::Closed`] if the invoice is no longer tracked.
+    pub fn recv(&self) -> Result<Invoice, SubscriberError> {
+        self.receiver.recv().map_err(SubscriberError::from)
+    }
+
+    /// Check for an update without blocking.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SubscriberError::Empty`] if no update is currently available, or
+    /// [`SubscriberError::Closed`] if the invoice is no longer tracked.
+    pub fn try_recv(&self) -> Result<Invoice, SubscriberError> {
+        self.receiver.try_recv().map_err(SubscriberError::from)
+    }
+}
+
+/// Registry of active [`Subscriber`]s, used by the scanning thread to publish invoice updates.
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    senders: Mutex<HashMap<InvoiceId, Vec<Sender<Invoice>>>>,
+}
+
+impl Subscribers {
+    pub(crate) fn subscribe(&self, id: InvoiceId) -> Subscriber {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.lock().unwrap().entry(id).or_default().push(sender);
+        Subscriber::new(receiver)
+    }
+
+    /// Push an update to every subscriber of `invoice`'s ID, dropping any that have hung up.
+    pub(crate) fn notify(&self, invoice: &Invoice) {
+        let mut senders = self.senders.lock().unwrap();
+        if let Some(list) = senders.get_mut(&invoice.id()) {
+            list.retain(|sender| sender.send(invoice.clone()).is_ok());
+        }
+    }
+
+    pub(crate) fn remove(&self, id: InvoiceId) {
+        self.senders.lock().unwrap().remove(&id);
+    }
+}
+
+/// Error returned when a [`Subscriber`] fails to receive an update.
+#[derive(Debug)]
+pub enum SubscriberError {
+    /// The invoice is no longer tracked (it was removed, or garbage-collected after expiring), so
+    /// no further updates will ever arrive.
+    Closed,
+    /// No update was available yet.
+    Empty,
+}
+
+impl From<RecvError> for SubscriberError {
+    fn from(_: RecvError) -> Self {
+        SubscriberError::Closed
+    }
+}
+
+impl From<TryRecvError> for SubscriberError {
+    fn from(e: TryRecvError) -> Self {
+        match e {
+            TryRecvError::Empty => SubscriberError::Empty,
+            TryRecvError::Disconnected => SubscriberError::Closed,
+        }
+    }
+}
+
+impl fmt::Display for SubscriberError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscriberError::Closed => write!(f, "invoice is no longer tracked"),
+            SubscriberError::Empty => write!(f, "no update currently available"),
+        }
+    }
+}
+
+impl std::error::Error for SubscriberError {}